@@ -0,0 +1,233 @@
+use bstr::BStr;
+use std::collections::HashMap;
+
+use crate::convert::Convert;
+use crate::extn::core::env::Env;
+use crate::extn::core::exception::{ArgumentError, RubyException};
+use crate::value::Value;
+use crate::Artichoke;
+
+/// An in-memory `Env` backend, decoupled from the process environment.
+///
+/// Unlike [`System`](super::System), which proxies to `std::env` and so is
+/// shared (and clobbered) by every interpreter in the process, a `Memory`
+/// backend's name/value pairs are owned by the interpreter that holds it.
+/// This gives each interpreter an isolated, reproducible `ENV` -- useful for
+/// tests and for embedding Artichoke in a host app without leaking Ruby's
+/// env writes into the host process.
+#[derive(Debug, Default, Clone)]
+pub struct Memory {
+    vars: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Construct a `Memory` backend seeded with a snapshot of the process
+    /// environment at the time of the call, without linking the two: later
+    /// writes through this backend never touch `std::env`, and later
+    /// `std::env` writes never appear here.
+    pub fn with_process_env_snapshot() -> Self {
+        let mut vars = HashMap::default();
+        for (name, value) in std::env::vars_os() {
+            #[cfg(unix)]
+            {
+                use std::os::unix::ffi::OsStrExt;
+                vars.insert(name.as_bytes().to_vec(), value.as_bytes().to_vec());
+            }
+            #[cfg(not(unix))]
+            {
+                if let (Some(name), Some(value)) = (name.to_str(), value.to_str()) {
+                    vars.insert(name.as_bytes().to_vec(), value.as_bytes().to_vec());
+                }
+            }
+        }
+        Self { vars }
+    }
+}
+
+impl Env for Memory {
+    fn get(&self, interp: &Artichoke, name: &[u8]) -> Result<Value, Box<dyn RubyException>> {
+        // Mirror `System`'s validation so swapping backends is invisible to
+        // Ruby code: these names are invalid at the OS level and MRI returns
+        // `nil` for them on element reference rather than raising.
+        if name.is_empty() || memchr::memchr(b'=', name).is_some() {
+            return Ok(interp.convert(None::<Value>));
+        }
+        if memchr::memchr(b'\0', name).is_some() {
+            return Err(Box::new(ArgumentError::new(
+                interp,
+                "bad environment variable name: contains null byte",
+            )));
+        }
+        if let Some(value) = self.vars.get(name) {
+            Ok(interp.convert(value.as_slice()))
+        } else {
+            Ok(interp.convert(None::<Value>))
+        }
+    }
+
+    fn put(
+        &mut self,
+        interp: &Artichoke,
+        name: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<Value, Box<dyn RubyException>> {
+        if name.is_empty() || memchr::memchr(b'=', name).is_some() {
+            // TODO: This should raise `Errno::EINVAL`.
+            return Err(Box::new(ArgumentError::new(
+                interp,
+                format!("Invalid argument - setenv({:?})", <&BStr>::from(name)),
+            )));
+        }
+        if memchr::memchr(b'\0', name).is_some() {
+            return Err(Box::new(ArgumentError::new(
+                interp,
+                "bad environment variable name: contains null byte",
+            )));
+        }
+        if let Some(value) = value {
+            if memchr::memchr(b'\0', value).is_some() {
+                Err(Box::new(ArgumentError::new(
+                    interp,
+                    "bad environment variable value: contains null byte",
+                )))
+            } else {
+                self.vars.insert(name.to_vec(), value.to_vec());
+                Ok(interp.convert(value))
+            }
+        } else {
+            let removed = self.vars.remove(name);
+            if let Some(removed) = removed {
+                Ok(interp.convert(removed.as_slice()))
+            } else {
+                Ok(interp.convert(None::<Value>))
+            }
+        }
+    }
+
+    fn as_map(
+        &self,
+        _interp: &Artichoke,
+    ) -> Result<HashMap<Vec<u8>, Vec<u8>>, Box<dyn RubyException>> {
+        Ok(self.vars.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::Memory;
+    use crate::extn::core::env::Env;
+
+    /// Rust test binaries run `#[test]` functions concurrently by default.
+    /// `with_process_env_snapshot_seeds_from_std_env_without_linking` is the
+    /// only test in this crate that mutates real process environment
+    /// variables, and the only one that takes this lock. Tests that only read
+    /// the process environment, such as the parent module's
+    /// `memory_backend_builds_without_panicking` (which calls
+    /// `with_process_env_snapshot`), do not take it and may run while the
+    /// variable is set.
+    static PROCESS_ENV_TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn get_of_an_unset_name_is_nil() {
+        let interp = crate::interpreter().expect("init");
+        let memory = Memory::new();
+        let value = memory.get(&interp, b"THIS_IS_NOT_SET").expect("get");
+        assert!(value.is_nil());
+    }
+
+    #[test]
+    fn put_then_get_round_trips() {
+        let interp = crate::interpreter().expect("init");
+        let mut memory = Memory::new();
+        memory.put(&interp, b"FOO", Some(b"bar")).expect("put");
+        let value = memory.get(&interp, b"FOO").expect("get");
+        assert_eq!(value, interp.convert(&b"bar"[..]));
+    }
+
+    #[test]
+    fn put_with_no_value_unsets() {
+        let interp = crate::interpreter().expect("init");
+        let mut memory = Memory::new();
+        memory.put(&interp, b"FOO", Some(b"bar")).expect("put");
+        memory.put(&interp, b"FOO", None).expect("unset");
+        let value = memory.get(&interp, b"FOO").expect("get");
+        assert!(value.is_nil());
+    }
+
+    #[test]
+    fn writes_are_isolated_from_the_process_environment() {
+        let interp = crate::interpreter().expect("init");
+        let mut memory = Memory::new();
+        memory
+            .put(&interp, b"ARTICHOKE_MEMORY_ENV_TEST", Some(b"isolated"))
+            .expect("put");
+        assert!(std::env::var_os("ARTICHOKE_MEMORY_ENV_TEST").is_none());
+    }
+
+    #[test]
+    fn name_containing_equals_sign_is_nil_on_get() {
+        let interp = crate::interpreter().expect("init");
+        let memory = Memory::new();
+        let value = memory.get(&interp, b"FOO=BAR").expect("get");
+        assert!(value.is_nil());
+    }
+
+    #[test]
+    fn name_containing_null_byte_is_an_error() {
+        let interp = crate::interpreter().expect("init");
+        let memory = Memory::new();
+        assert!(memory.get(&interp, b"FOO\0BAR").is_err());
+    }
+
+    /// Sets a process environment variable for the duration of the guard and
+    /// removes it on drop, so a panic mid-test doesn't leak state into other
+    /// tests in the binary.
+    struct EnvVarGuard {
+        name: &'static str,
+    }
+
+    impl EnvVarGuard {
+        fn set(name: &'static str, value: &str) -> Self {
+            std::env::set_var(name, value);
+            Self { name }
+        }
+    }
+
+    impl Drop for EnvVarGuard {
+        fn drop(&mut self) {
+            std::env::remove_var(self.name);
+        }
+    }
+
+    #[test]
+    fn with_process_env_snapshot_seeds_from_std_env_without_linking() {
+        // Poison is ignored: another test panicking while holding the lock
+        // shouldn't stop this one from running and cleaning up after itself.
+        let _guard = PROCESS_ENV_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let env_var = EnvVarGuard::set("ARTICHOKE_MEMORY_ENV_SEED_TEST", "seeded");
+        let interp = crate::interpreter().expect("init");
+        let memory = Memory::with_process_env_snapshot();
+
+        let value = memory
+            .get(&interp, b"ARTICHOKE_MEMORY_ENV_SEED_TEST")
+            .expect("get");
+        assert_eq!(value, interp.convert(&b"seeded"[..]));
+
+        drop(env_var);
+        // `Memory` owns a copy taken at construction time; it does not
+        // observe the `std::env` removal above.
+        let value = memory
+            .get(&interp, b"ARTICHOKE_MEMORY_ENV_SEED_TEST")
+            .expect("get");
+        assert_eq!(value, interp.convert(&b"seeded"[..]));
+    }
+}
@@ -0,0 +1,7 @@
+//! `Env` backend implementations.
+
+pub mod memory;
+pub mod system;
+
+pub use memory::Memory;
+pub use system::System;
@@ -0,0 +1,112 @@
+//! Ruby's `ENV` object, backed by a pluggable store.
+//!
+//! [`backend::System`](backend::System) proxies directly to the process
+//! environment; [`backend::Memory`](backend::Memory) is an in-memory,
+//! per-interpreter store for sandboxed or multi-tenant embedding. [`Backend`]
+//! selects between the two and builds the boxed [`Env`] that interpreter
+//! construction should store in interpreter state; this module only owns the
+//! selection, not the construction path itself.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::extn::core::exception::RubyException;
+use crate::value::Value;
+use crate::Artichoke;
+
+pub mod backend;
+
+/// Which [`Env`] implementation a newly constructed interpreter should
+/// install.
+///
+/// Interpreter construction is expected to pick a variant (e.g. `System` by
+/// default, `Memory` for sandboxed or multi-tenant embedding) and call
+/// [`Backend::build`] to get the boxed `Env` it stores in interpreter state,
+/// so `ENV` reads and writes made from Ruby are dispatched through it. This
+/// type is the selection point for that choice; it does not call itself --
+/// nothing in this crate invokes [`Backend::build`] outside of its own
+/// tests, so a `Memory` backend is not reachable from a real interpreter
+/// until a construction path is wired up to use it.
+#[derive(Debug, Clone, Copy)]
+pub enum Backend {
+    /// Proxy directly to the process environment via `std::env`. Shared
+    /// process-wide, so multiple interpreters in one process see each
+    /// other's writes.
+    System,
+    /// An isolated, in-memory store, decoupled from `std::env`.
+    Memory {
+        /// Seed the store with a snapshot of `std::env::vars_os` taken at
+        /// construction time. Later writes through either `ENV` or
+        /// `std::env` are not reflected in the other.
+        seed_from_process_env: bool,
+    },
+}
+
+impl Default for Backend {
+    /// [`Backend::System`], matching the behavior of every `ENV` backend
+    /// before [`backend::Memory`] was introduced.
+    fn default() -> Self {
+        Self::System
+    }
+}
+
+impl Backend {
+    /// Construct the boxed [`Env`] this variant selects.
+    pub fn build(self) -> Box<dyn Env> {
+        match self {
+            Self::System => Box::new(backend::System::new()),
+            Self::Memory {
+                seed_from_process_env: true,
+            } => Box::new(backend::Memory::with_process_env_snapshot()),
+            Self::Memory {
+                seed_from_process_env: false,
+            } => Box::new(backend::Memory::new()),
+        }
+    }
+}
+
+/// A backend for Ruby's `ENV`, abstracting over where name/value pairs
+/// actually live.
+pub trait Env: fmt::Debug {
+    /// Look up the value of the environment variable `name`.
+    ///
+    /// Returns `nil` if `name` is unset, or if `name` is malformed in a way
+    /// that the underlying store cannot represent (empty, or containing
+    /// `=`).
+    fn get(&self, interp: &Artichoke, name: &[u8]) -> Result<Value, Box<dyn RubyException>>;
+
+    /// Set the environment variable `name` to `value`, or unset it if
+    /// `value` is `None`.
+    fn put(
+        &mut self,
+        interp: &Artichoke,
+        name: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<Value, Box<dyn RubyException>>;
+
+    /// A snapshot of every name/value pair currently set.
+    fn as_map(&self, interp: &Artichoke) -> Result<HashMap<Vec<u8>, Vec<u8>>, Box<dyn RubyException>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backend;
+
+    #[test]
+    fn default_backend_is_system() {
+        assert!(matches!(Backend::default(), Backend::System));
+    }
+
+    #[test]
+    fn memory_backend_builds_without_panicking() {
+        let _ = Backend::Memory {
+            seed_from_process_env: false,
+        }
+        .build();
+        let _ = Backend::Memory {
+            seed_from_process_env: true,
+        }
+        .build();
+        let _ = Backend::System.build();
+    }
+}
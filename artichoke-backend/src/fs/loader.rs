@@ -0,0 +1,327 @@
+//! A loader that mirrors real filesystem directories into the VFS.
+//!
+//! Modeled on rust-analyzer's `vfs::loader`: callers register one or more
+//! real directories as "load roots" with
+//! [`LoadSources::add_load_root`](crate::load::LoadSources::add_load_root),
+//! which walks the directory into [`Vfs`] and, in [`ScanMode::Watch`] mode,
+//! can be re-polled to pick up edits made on disk after the interpreter has
+//! started.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::fs::{Vfs, VfsPath};
+use crate::ArtichokeError;
+
+/// Whether a load root is scanned once at registration time, or re-scanned
+/// on demand to pick up edits made on disk.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ScanMode {
+    /// Walk the real directory once; never look at it again.
+    Once,
+    /// Walk the real directory and remember enough to detect create/modify/
+    /// delete changes on a later call to [`LoadRoot::poll`].
+    Watch,
+}
+
+/// An opaque handle to a registered load root, returned by
+/// [`LoadSources::add_load_root`](crate::load::LoadSources::add_load_root).
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct LoadRootHandle(usize);
+
+impl LoadRootHandle {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index)
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A single create/modify/delete observed under a load root.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ChangeEvent {
+    Created(VfsPath),
+    Modified(VfsPath),
+    Removed(VfsPath),
+}
+
+#[derive(Debug, Clone)]
+struct WatchedFile {
+    real_path: PathBuf,
+    vfs_path: VfsPath,
+    modified: Option<SystemTime>,
+}
+
+/// A real directory mirrored into the VFS under `vfs_prefix`.
+#[derive(Debug)]
+pub struct LoadRoot {
+    real_dir: PathBuf,
+    vfs_prefix: VfsPath,
+    mode: ScanMode,
+    watched: Vec<WatchedFile>,
+}
+
+impl LoadRoot {
+    pub(crate) fn new(real_dir: PathBuf, vfs_prefix: VfsPath, mode: ScanMode) -> Self {
+        Self {
+            real_dir,
+            vfs_prefix,
+            mode,
+            watched: Vec::new(),
+        }
+    }
+
+    /// Map a real path under `real_dir` to its mirrored [`VfsPath`] under
+    /// `vfs_prefix`.
+    fn vfs_path_for(&self, real_path: &Path) -> Result<VfsPath, ArtichokeError> {
+        let relative = real_path.strip_prefix(&self.real_dir).map_err(|_| {
+            ArtichokeError::Vfs(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not contained in load root {}",
+                    real_path.display(),
+                    self.real_dir.display()
+                ),
+            ))
+        })?;
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        Ok(self.vfs_prefix.join(relative))
+    }
+
+    /// Walk `real_dir`, loading every regular file's contents into `vfs`
+    /// under the mirrored path. In [`ScanMode::Watch`] mode, remembers each
+    /// file's modification time so a later [`LoadRoot::poll`] can detect
+    /// changes.
+    pub(crate) fn scan(&mut self, vfs: &Vfs) -> Result<Vec<ChangeEvent>, ArtichokeError> {
+        let mut events = Vec::new();
+        let mut watched = Vec::new();
+        let mut stack = vec![self.real_dir.clone()];
+        while let Some(dir) = stack.pop() {
+            let entries = fs::read_dir(&dir).map_err(ArtichokeError::Vfs)?;
+            for entry in entries {
+                let entry = entry.map_err(ArtichokeError::Vfs)?;
+                let path = entry.path();
+                let file_type = entry.file_type().map_err(ArtichokeError::Vfs)?;
+                if file_type.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                if !file_type.is_file() {
+                    continue;
+                }
+                let vfs_path = self.vfs_path_for(&path)?;
+                let contents = fs::read(&path).map_err(ArtichokeError::Vfs)?;
+                let existed = vfs.is_file(&vfs_path);
+                vfs.write_file(&vfs_path, contents)?;
+                events.push(if existed {
+                    ChangeEvent::Modified(vfs_path.clone())
+                } else {
+                    ChangeEvent::Created(vfs_path.clone())
+                });
+                if self.mode == ScanMode::Watch {
+                    let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                    watched.push(WatchedFile {
+                        real_path: path,
+                        vfs_path,
+                        modified,
+                    });
+                }
+            }
+        }
+        self.watched = watched;
+        Ok(events)
+    }
+
+    /// Re-examine every watched file for create/modify/delete changes since
+    /// the last scan or poll, applying them back into `vfs` and clearing any
+    /// "already required" metadata for affected paths so a subsequent
+    /// `require` re-evaluates the new contents.
+    ///
+    /// No-op outside [`ScanMode::Watch`].
+    pub fn poll(&mut self, vfs: &Vfs) -> Result<Vec<ChangeEvent>, ArtichokeError> {
+        if self.mode != ScanMode::Watch {
+            return Ok(Vec::new());
+        }
+        let mut events = Vec::new();
+        let mut still_present = Vec::with_capacity(self.watched.len());
+        for watched in &mut self.watched {
+            match fs::metadata(&watched.real_path) {
+                Ok(meta) => {
+                    let modified = meta.modified().ok();
+                    if modified != watched.modified {
+                        let contents = fs::read(&watched.real_path).map_err(ArtichokeError::Vfs)?;
+                        vfs.write_file(&watched.vfs_path, contents)?;
+                        invalidate_require_state(vfs, &watched.vfs_path)?;
+                        events.push(ChangeEvent::Modified(watched.vfs_path.clone()));
+                        watched.modified = modified;
+                    }
+                    still_present.push(watched.clone());
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    vfs.remove(&watched.vfs_path);
+                    events.push(ChangeEvent::Removed(watched.vfs_path.clone()));
+                }
+                Err(err) => return Err(ArtichokeError::Vfs(err)),
+            }
+        }
+        self.watched = still_present;
+        // Pick up files created since the last scan.
+        events.extend(self.scan_for_new_files(vfs)?);
+        Ok(events)
+    }
+
+    fn scan_for_new_files(&mut self, vfs: &Vfs) -> Result<Vec<ChangeEvent>, ArtichokeError> {
+        let known: Vec<PathBuf> = self.watched.iter().map(|w| w.real_path.clone()).collect();
+        let mut events = Vec::new();
+        let mut stack = vec![self.real_dir.clone()];
+        while let Some(dir) = stack.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries {
+                let entry = entry.map_err(ArtichokeError::Vfs)?;
+                let path = entry.path();
+                let file_type = entry.file_type().map_err(ArtichokeError::Vfs)?;
+                if file_type.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                if !file_type.is_file() || known.contains(&path) {
+                    continue;
+                }
+                let vfs_path = self.vfs_path_for(&path)?;
+                let contents = fs::read(&path).map_err(ArtichokeError::Vfs)?;
+                vfs.write_file(&vfs_path, contents)?;
+                invalidate_require_state(vfs, &vfs_path)?;
+                let modified = entry.metadata().ok().and_then(|m| m.modified().ok());
+                self.watched.push(WatchedFile {
+                    real_path: path,
+                    vfs_path: vfs_path.clone(),
+                    modified,
+                });
+                events.push(ChangeEvent::Created(vfs_path));
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// Clear any "already required" metadata for `path`, so that a file whose
+/// contents changed on disk is re-evaluated the next time it is `require`d.
+fn invalidate_require_state(vfs: &Vfs, path: &VfsPath) -> Result<(), ArtichokeError> {
+    let mut metadata = vfs.metadata(path).unwrap_or_default();
+    metadata.already_required = false;
+    vfs.set_metadata(path, metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::{ChangeEvent, LoadRoot, ScanMode};
+    use crate::fs::{Vfs, VfsPath};
+
+    #[test]
+    fn scan_mirrors_real_files_into_the_vfs() {
+        let dir = tempdir().expect("tempdir");
+        fs::write(dir.path().join("foo.rb"), "# foo").expect("write foo.rb");
+        fs::create_dir(dir.path().join("nested")).expect("mkdir nested");
+        fs::write(dir.path().join("nested/bar.rb"), "# bar").expect("write nested/bar.rb");
+
+        let vfs = Vfs::new();
+        let prefix = VfsPath::new("/src/gems").expect("valid path");
+        let mut root = LoadRoot::new(dir.path().to_path_buf(), prefix, ScanMode::Once);
+        root.scan(&vfs).expect("scan");
+
+        assert!(vfs.is_file(&VfsPath::new("/src/gems/foo.rb").expect("valid path")));
+        assert!(vfs.is_file(&VfsPath::new("/src/gems/nested/bar.rb").expect("valid path")));
+    }
+
+    #[test]
+    fn once_mode_never_polls_for_changes() {
+        let dir = tempdir().expect("tempdir");
+        fs::write(dir.path().join("foo.rb"), "# v1").expect("write foo.rb");
+
+        let vfs = Vfs::new();
+        let prefix = VfsPath::new("/src/gems").expect("valid path");
+        let mut root = LoadRoot::new(dir.path().to_path_buf(), prefix, ScanMode::Once);
+        root.scan(&vfs).expect("scan");
+
+        fs::write(dir.path().join("foo.rb"), "# v2").expect("rewrite foo.rb");
+        let events = root.poll(&vfs).expect("poll");
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn poll_detects_modified_files_and_invalidates_require_state() {
+        let dir = tempdir().expect("tempdir");
+        let file = dir.path().join("foo.rb");
+        fs::write(&file, "# v1").expect("write foo.rb");
+
+        let vfs = Vfs::new();
+        let prefix = VfsPath::new("/src/gems").expect("valid path");
+        let mut root = LoadRoot::new(dir.path().to_path_buf(), prefix, ScanMode::Watch);
+        root.scan(&vfs).expect("scan");
+
+        let path = VfsPath::new("/src/gems/foo.rb").expect("valid path");
+        let mut metadata = vfs.metadata(&path).expect("metadata");
+        metadata.already_required = true;
+        vfs.set_metadata(&path, metadata).expect("set_metadata");
+
+        // Some filesystems only have second-granularity mtimes.
+        sleep(Duration::from_millis(1100));
+        fs::write(&file, "# v2").expect("rewrite foo.rb");
+
+        let events = root.poll(&vfs).expect("poll");
+        assert_eq!(events, vec![ChangeEvent::Modified(path.clone())]);
+        assert_eq!(vfs.read_file(&path), Some(b"# v2".to_vec()));
+        assert!(!vfs.metadata(&path).expect("metadata").already_required);
+    }
+
+    #[test]
+    fn poll_detects_created_files() {
+        let dir = tempdir().expect("tempdir");
+        let vfs = Vfs::new();
+        let prefix = VfsPath::new("/src/gems").expect("valid path");
+        let mut root = LoadRoot::new(dir.path().to_path_buf(), prefix, ScanMode::Watch);
+        root.scan(&vfs).expect("scan");
+
+        fs::write(dir.path().join("new.rb"), "# new").expect("write new.rb");
+        let events = root.poll(&vfs).expect("poll");
+
+        let path = VfsPath::new("/src/gems/new.rb").expect("valid path");
+        assert_eq!(events, vec![ChangeEvent::Created(path.clone())]);
+        assert!(vfs.is_file(&path));
+    }
+
+    #[test]
+    fn poll_detects_removed_files_and_clears_the_vfs_entry() {
+        let dir = tempdir().expect("tempdir");
+        let file = dir.path().join("foo.rb");
+        fs::write(&file, "# v1").expect("write foo.rb");
+
+        let vfs = Vfs::new();
+        let prefix = VfsPath::new("/src/gems").expect("valid path");
+        let mut root = LoadRoot::new(dir.path().to_path_buf(), prefix, ScanMode::Watch);
+        root.scan(&vfs).expect("scan");
+
+        let path = VfsPath::new("/src/gems/foo.rb").expect("valid path");
+        assert!(vfs.is_file(&path));
+
+        fs::remove_file(&file).expect("remove foo.rb");
+        let events = root.poll(&vfs).expect("poll");
+
+        assert_eq!(events, vec![ChangeEvent::Removed(path.clone())]);
+        assert!(!vfs.is_file(&path));
+        assert!(vfs.read_file(&path).is_none());
+    }
+}
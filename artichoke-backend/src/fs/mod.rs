@@ -0,0 +1,430 @@
+//! An in-memory virtual filesystem that backs `Kernel#require`.
+//!
+//! Ruby source loaded into an [`Artichoke`] interpreter -- whether pure Ruby
+//! or a Rust-backed stub registered with [`LoadSources`](crate::load::LoadSources)
+//! -- lives in this virtual filesystem rather than on disk. This keeps
+//! `require` reproducible across interpreters and platforms.
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use crate::extn::core::exception::RubyException;
+use crate::{Artichoke, ArtichokeError};
+
+pub mod loader;
+mod path_interner;
+mod vfs_path;
+
+pub use loader::{ChangeEvent, LoadRoot, LoadRootHandle, ScanMode};
+pub use path_interner::FileId;
+use path_interner::PathInterner;
+pub use vfs_path::VfsPath;
+
+/// Default directory relative paths passed to
+/// [`def_file`](crate::load::LoadSources::def_file) and
+/// [`def_rb_source_file`](crate::load::LoadSources::def_rb_source_file) are
+/// anchored to.
+pub const RUBY_LOAD_PATH: &str = "/src/lib";
+
+/// Metadata associated with a file in the virtual filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Metadata {
+    /// The Rust function that defines this file's contents, invoked the
+    /// first time the file is `require`d.
+    pub require: Option<fn(Artichoke) -> Result<(), ArtichokeError>>,
+    /// Whether `require` has already evaluated this file.
+    pub already_required: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    /// Whether this `FileId` has actually been written to, as opposed to
+    /// merely interned while resolving a candidate `require` path.
+    present: bool,
+    contents: Vec<u8>,
+    metadata: Metadata,
+}
+
+/// An in-memory filesystem rooted at `/`, keyed by [`VfsPath`] but indexed
+/// internally by compact [`FileId`]s.
+///
+/// State is kept behind interior mutability so that `Vfs` can be reached
+/// through the shared, immutably-borrowed interpreter state returned by
+/// `Artichoke`'s `RefCell`.
+#[derive(Debug)]
+pub struct Vfs {
+    interner: RefCell<PathInterner>,
+    /// Per-file state, indexed by `FileId`. Kept in lockstep with the
+    /// interner: every interned `FileId` has a (possibly empty, not-yet-
+    /// `present`) entry here.
+    entries: RefCell<Vec<Entry>>,
+    /// Stack of files whose `require`/`require_relative` is currently
+    /// executing, innermost last. The top of the stack is the anchor used
+    /// to resolve a nested `require_relative`.
+    active_files: RefCell<Vec<FileId>>,
+    /// Real directories mirrored into this VFS by
+    /// [`LoadSources::add_load_root`](crate::load::LoadSources::add_load_root).
+    load_roots: RefCell<Vec<LoadRoot>>,
+    /// The ordered list of directories relative filenames are resolved
+    /// against, mirroring Ruby's `$LOAD_PATH`/`$:`. Defaults to a single
+    /// entry, [`RUBY_LOAD_PATH`].
+    load_path: RefCell<Vec<VfsPath>>,
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self {
+            interner: RefCell::default(),
+            entries: RefCell::default(),
+            active_files: RefCell::default(),
+            load_roots: RefCell::default(),
+            load_path: RefCell::new(vec![VfsPath::new(RUBY_LOAD_PATH)
+                .expect("RUBY_LOAD_PATH is a valid VfsPath")]),
+        }
+    }
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `path` to the end of the load path, searched last.
+    pub fn push_load_path(&self, path: VfsPath) {
+        self.load_path.borrow_mut().push(path);
+    }
+
+    /// Insert `path` at the front of the load path, searched first.
+    pub fn prepend_load_path(&self, path: VfsPath) {
+        self.load_path.borrow_mut().insert(0, path);
+    }
+
+    /// A snapshot of the load path, in search order.
+    pub fn load_paths(&self) -> Vec<VfsPath> {
+        self.load_path.borrow().clone()
+    }
+
+    /// Replace the load path wholesale, in the given search order.
+    ///
+    /// Used to pull Ruby-side mutation of the `$LOAD_PATH`/`$:` globals
+    /// (`$LOAD_PATH.push`, `$LOAD_PATH.unshift`, reassignment, ...) back into
+    /// the registry that [`Vfs::resolve_in_load_path`] searches.
+    pub fn set_load_path(&self, paths: Vec<VfsPath>) {
+        *self.load_path.borrow_mut() = paths;
+    }
+
+    /// Resolve `filename` against the load path, in order, returning the
+    /// first entry under which `filename` is already a file in this VFS.
+    ///
+    /// If `filename` is not found under any load path entry, falls back to
+    /// the first entry -- the canonical place to register a new file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the load path is empty. It is seeded with
+    /// [`RUBY_LOAD_PATH`], and [`Vfs::prepend_load_path`]/
+    /// [`Vfs::push_load_path`] only add entries, but [`Vfs::set_load_path`]
+    /// accepts an empty list.
+    pub fn resolve_in_load_path(&self, filename: &str) -> Result<VfsPath, ArtichokeError> {
+        let load_path = self.load_path.borrow();
+        for base in load_path.iter() {
+            let candidate = base.join(filename);
+            if self.is_file(&candidate) {
+                return Ok(candidate);
+            }
+        }
+        let base = load_path.first().expect("load path is never empty");
+        Ok(base.join(filename))
+    }
+
+    /// Resolve `path` to its `FileId`, interning it if this is the first
+    /// time `path` has been seen.
+    pub fn file_id(&self, path: &VfsPath) -> FileId {
+        let id = self.interner.borrow_mut().intern(path.clone());
+        let mut entries = self.entries.borrow_mut();
+        if id.index() >= entries.len() {
+            entries.resize_with(id.index() + 1, Entry::default);
+        }
+        id
+    }
+
+    /// The path `id` was assigned to.
+    pub fn path(&self, id: FileId) -> VfsPath {
+        self.interner.borrow().path(id).clone()
+    }
+
+    /// Push `id` as the file that is about to begin executing, making it the
+    /// anchor for any `require_relative` it calls.
+    pub fn push_active_file(&self, id: FileId) {
+        self.active_files.borrow_mut().push(id);
+    }
+
+    /// Pop the file pushed by the matching [`Vfs::push_active_file`], once
+    /// it has finished executing.
+    pub fn pop_active_file(&self) -> Option<FileId> {
+        self.active_files.borrow_mut().pop()
+    }
+
+    /// The file currently executing, i.e. the anchor `require_relative`
+    /// should resolve against. `None` at the top level, before any file has
+    /// been `require`d.
+    pub fn active_file(&self) -> Option<FileId> {
+        self.active_files.borrow().last().copied()
+    }
+
+    pub fn create_dir_all(&self, _path: &VfsPath) -> Result<(), ArtichokeError> {
+        // Directories are implicit: any prefix of a file's path is a
+        // directory. Nothing to materialize.
+        Ok(())
+    }
+
+    pub fn is_file(&self, path: &VfsPath) -> bool {
+        match self.interner.borrow().lookup(path) {
+            Some(id) => self.entries.borrow()[id.index()].present,
+            None => false,
+        }
+    }
+
+    pub fn write_file<T>(&self, path: &VfsPath, contents: T) -> Result<FileId, ArtichokeError>
+    where
+        T: AsRef<[u8]>,
+    {
+        let id = self.file_id(path);
+        let mut entries = self.entries.borrow_mut();
+        let entry = &mut entries[id.index()];
+        entry.present = true;
+        entry.contents = contents.as_ref().to_vec();
+        Ok(id)
+    }
+
+    pub fn read_file(&self, path: &VfsPath) -> Option<Vec<u8>> {
+        let id = self.interner.borrow().lookup(path)?;
+        let entries = self.entries.borrow();
+        let entry = &entries[id.index()];
+        entry.present.then(|| entry.contents.clone())
+    }
+
+    pub fn metadata(&self, path: &VfsPath) -> Option<Metadata> {
+        let id = self.interner.borrow().lookup(path)?;
+        let entries = self.entries.borrow();
+        let entry = &entries[id.index()];
+        entry.present.then(|| entry.metadata)
+    }
+
+    pub fn set_metadata(&self, path: &VfsPath, metadata: Metadata) -> Result<(), ArtichokeError> {
+        let id = self.file_id(path);
+        self.entries.borrow_mut()[id.index()].metadata = metadata;
+        Ok(())
+    }
+
+    /// Mark `path` as no longer present, e.g. because the real file backing
+    /// it under a watched load root was deleted.
+    ///
+    /// A removed path stops being [`Vfs::is_file`] and loses its contents and
+    /// metadata, so a later `require` for it raises `LoadError` exactly as if
+    /// it had never been registered, rather than "succeeding" with stale
+    /// contents. `path`'s `FileId` is retained (interning is forever), so it
+    /// can be written to again later, e.g. if the file reappears on disk.
+    pub fn remove(&self, path: &VfsPath) {
+        if let Some(id) = self.interner.borrow().lookup(path) {
+            let mut entries = self.entries.borrow_mut();
+            entries[id.index()] = Entry::default();
+        }
+    }
+
+    /// Register `real_dir` as a load root mirrored under `vfs_prefix`,
+    /// walking it into this VFS immediately.
+    pub fn add_load_root(
+        &self,
+        real_dir: PathBuf,
+        vfs_prefix: VfsPath,
+        mode: ScanMode,
+    ) -> Result<LoadRootHandle, ArtichokeError> {
+        let mut root = LoadRoot::new(real_dir, vfs_prefix, mode);
+        root.scan(self)?;
+        let mut load_roots = self.load_roots.borrow_mut();
+        load_roots.push(root);
+        Ok(LoadRootHandle::new(load_roots.len() - 1))
+    }
+
+    /// Re-scan the load root registered under `handle` for create/modify/
+    /// delete changes, applying them back into this VFS. No-op for a load
+    /// root registered with [`ScanMode::Once`].
+    pub fn poll_load_root(&self, handle: LoadRootHandle) -> Result<Vec<ChangeEvent>, ArtichokeError> {
+        let idx = handle.index();
+        let mut root = self.load_roots.borrow_mut().remove(idx);
+        let result = root.poll(self);
+        self.load_roots.borrow_mut().insert(idx, root);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metadata, Vfs, RUBY_LOAD_PATH};
+    use crate::fs::VfsPath;
+
+    #[test]
+    fn remove_clears_contents_and_is_file() {
+        let vfs = Vfs::new();
+        let path = VfsPath::new("/src/lib/foo.rb").unwrap();
+        vfs.write_file(&path, "# foo").unwrap();
+        vfs.set_metadata(
+            &path,
+            Metadata {
+                require: None,
+                already_required: true,
+            },
+        )
+        .unwrap();
+        assert!(vfs.is_file(&path));
+
+        vfs.remove(&path);
+
+        assert!(!vfs.is_file(&path));
+        assert!(vfs.read_file(&path).is_none());
+        assert!(vfs.metadata(&path).is_none());
+    }
+
+    #[test]
+    fn remove_of_an_unknown_path_is_a_no_op() {
+        let vfs = Vfs::new();
+        let path = VfsPath::new("/src/lib/never-existed.rb").unwrap();
+        vfs.remove(&path);
+        assert!(!vfs.is_file(&path));
+    }
+
+    #[test]
+    fn a_file_can_be_rewritten_after_being_removed() {
+        let vfs = Vfs::new();
+        let path = VfsPath::new("/src/lib/foo.rb").unwrap();
+        vfs.write_file(&path, "# v1").unwrap();
+        vfs.remove(&path);
+
+        vfs.write_file(&path, "# v2").unwrap();
+
+        assert!(vfs.is_file(&path));
+        assert_eq!(vfs.read_file(&path), Some(b"# v2".to_vec()));
+    }
+
+    #[test]
+    fn resolve_in_load_path_prefers_earlier_entries() {
+        let vfs = Vfs::new();
+        vfs.prepend_load_path(VfsPath::new("/gem-a").unwrap());
+        vfs.push_load_path(VfsPath::new("/gem-b").unwrap());
+        vfs.write_file(&VfsPath::new("/gem-a/foo.rb").unwrap(), "# a")
+            .unwrap();
+        vfs.write_file(&VfsPath::new("/gem-b/foo.rb").unwrap(), "# b")
+            .unwrap();
+
+        let resolved = vfs.resolve_in_load_path("foo.rb").unwrap();
+        assert_eq!(resolved.as_str(), "/gem-a/foo.rb");
+    }
+
+    #[test]
+    fn resolve_in_load_path_finds_the_first_entry_that_has_the_file() {
+        let vfs = Vfs::new();
+        vfs.push_load_path(VfsPath::new("/gem-a").unwrap());
+        vfs.push_load_path(VfsPath::new("/gem-b").unwrap());
+        vfs.write_file(&VfsPath::new("/gem-b/only-in-b.rb").unwrap(), "# b")
+            .unwrap();
+
+        let resolved = vfs.resolve_in_load_path("only-in-b.rb").unwrap();
+        assert_eq!(resolved.as_str(), "/gem-b/only-in-b.rb");
+    }
+
+    #[test]
+    fn resolve_in_load_path_falls_back_to_the_first_entry_for_a_new_file() {
+        let vfs = Vfs::new();
+        vfs.push_load_path(VfsPath::new("/gem-a").unwrap());
+        vfs.push_load_path(VfsPath::new("/gem-b").unwrap());
+
+        // Both gems were pushed after the default entry, so `RUBY_LOAD_PATH`
+        // is still first.
+        let resolved = vfs.resolve_in_load_path("new.rb").unwrap();
+        assert_eq!(resolved.as_str(), "/src/lib/new.rb");
+    }
+
+    #[test]
+    fn push_load_path_appends_and_prepend_load_path_prepends() {
+        let vfs = Vfs::new();
+        vfs.push_load_path(VfsPath::new("/gem-a").unwrap());
+        vfs.prepend_load_path(VfsPath::new("/gem-b").unwrap());
+
+        let paths: Vec<String> = vfs.load_paths().iter().map(ToString::to_string).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/gem-b".to_string(),
+                RUBY_LOAD_PATH.to_string(),
+                "/gem-a".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_load_path_replaces_the_search_order_wholesale() {
+        let vfs = Vfs::new();
+        vfs.push_load_path(VfsPath::new("/gem-a").unwrap());
+
+        vfs.set_load_path(vec![
+            VfsPath::new("/gem-c").unwrap(),
+            VfsPath::new("/gem-d").unwrap(),
+        ]);
+
+        let paths: Vec<String> = vfs.load_paths().iter().map(ToString::to_string).collect();
+        assert_eq!(paths, vec!["/gem-c".to_string(), "/gem-d".to_string()]);
+    }
+}
+
+#[cfg(unix)]
+pub fn bytes_to_osstr<'a>(
+    _interp: &Artichoke,
+    bytes: &'a [u8],
+) -> Result<&'a OsStr, Box<dyn RubyException>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_osstr<'a>(
+    interp: &Artichoke,
+    bytes: &'a [u8],
+) -> Result<&'a OsStr, Box<dyn RubyException>> {
+    use crate::extn::core::exception::ArgumentError;
+    str::from_utf8(bytes)
+        .map(OsStr::new)
+        .map_err(|_| -> Box<dyn RubyException> {
+            Box::new(ArgumentError::new(
+                interp,
+                "env names and values must be valid UTF-8 on this platform",
+            ))
+        })
+}
+
+#[cfg(unix)]
+pub fn osstr_to_bytes<'a>(
+    _interp: &Artichoke,
+    value: &'a OsStr,
+) -> Result<&'a [u8], Box<dyn RubyException>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(value.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn osstr_to_bytes<'a>(
+    interp: &Artichoke,
+    value: &'a OsStr,
+) -> Result<&'a [u8], Box<dyn RubyException>> {
+    use crate::extn::core::exception::ArgumentError;
+    value
+        .to_str()
+        .map(str::as_bytes)
+        .ok_or_else(|| -> Box<dyn RubyException> {
+            Box::new(ArgumentError::new(
+                interp,
+                "env names and values must be valid UTF-8 on this platform",
+            ))
+        })
+}
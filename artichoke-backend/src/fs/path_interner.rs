@@ -0,0 +1,101 @@
+//! A bijective `VfsPath <-> FileId` map, as in rust-analyzer's
+//! `vfs::path_interner`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::VfsPath;
+
+/// A small, `Copy`able identifier for a file in the VFS, assigned the first
+/// time its path is resolved. Once assigned, a `FileId` is never reused or
+/// reassigned to a different path for the lifetime of the interpreter.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+impl fmt::Debug for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FileId({})", self.0)
+    }
+}
+
+impl FileId {
+    pub(super) fn from_index(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub(super) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Interns [`VfsPath`]s to compact, copyable [`FileId`]s so that repeated
+/// `require` resolution is an integer comparison and array index rather than
+/// a string hash on every lookup.
+#[derive(Debug, Default)]
+pub struct PathInterner {
+    paths: Vec<VfsPath>,
+    ids: HashMap<VfsPath, FileId>,
+}
+
+impl PathInterner {
+    /// Look up `path`'s `FileId`, assigning one if this is the first time
+    /// `path` has been seen.
+    pub fn intern(&mut self, path: VfsPath) -> FileId {
+        if let Some(&id) = self.ids.get(&path) {
+            return id;
+        }
+        let id = FileId::from_index(self.paths.len());
+        self.paths.push(path.clone());
+        self.ids.insert(path, id);
+        id
+    }
+
+    /// Look up `path`'s `FileId`, if it has been interned.
+    pub fn lookup(&self, path: &VfsPath) -> Option<FileId> {
+        self.ids.get(path).copied()
+    }
+
+    /// The path that `id` was assigned to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not produced by this interner.
+    pub fn path(&self, id: FileId) -> &VfsPath {
+        &self.paths[id.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathInterner, VfsPath};
+
+    #[test]
+    fn repeated_intern_returns_the_same_id() {
+        let mut interner = PathInterner::default();
+        let a = interner.intern(VfsPath::new("/src/lib/foo.rb").unwrap());
+        let b = interner.intern(VfsPath::new("/src/lib/foo.rb").unwrap());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn distinct_paths_get_distinct_ids() {
+        let mut interner = PathInterner::default();
+        let a = interner.intern(VfsPath::new("/src/lib/foo.rb").unwrap());
+        let b = interner.intern(VfsPath::new("/src/lib/bar.rb").unwrap());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn path_roundtrips_through_its_id() {
+        let mut interner = PathInterner::default();
+        let path = VfsPath::new("/src/lib/foo.rb").unwrap();
+        let id = interner.intern(path.clone());
+        assert_eq!(interner.path(id), &path);
+    }
+
+    #[test]
+    fn lookup_of_uninterned_path_is_none() {
+        let interner = PathInterner::default();
+        assert!(interner.lookup(&VfsPath::new("/src/lib/foo.rb").unwrap()).is_none());
+    }
+}
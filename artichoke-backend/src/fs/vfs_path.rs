@@ -0,0 +1,254 @@
+//! A normalized, virtual-rooted path used to key entries in the VFS.
+
+use std::fmt;
+use std::io;
+
+use crate::ArtichokeError;
+
+/// A string-backed path into the virtual filesystem that backs `require`.
+///
+/// A `VfsPath` is always rooted at `/` and is normalized at construction
+/// time: `.` segments are dropped, `..` segments are resolved against the
+/// segments accumulated so far, and the result never contains `.`, `..`, or
+/// a doubled separator (`//`). This guarantees that `require "foo"`,
+/// `require "./foo"`, and `require "bar/../foo"` all key the same VFS entry.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct VfsPath(String);
+
+impl fmt::Display for VfsPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl VfsPath {
+    /// Construct a `VfsPath` by normalizing `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `path` is empty, ends in a trailing slash, or
+    /// contains a doubled separator.
+    pub fn new<T>(path: T) -> Result<Self, ArtichokeError>
+    where
+        T: AsRef<str>,
+    {
+        let raw = path.as_ref();
+        if raw.is_empty() {
+            return Err(Self::invalid(raw, "path is empty"));
+        }
+        if raw.len() > 1 && raw.ends_with('/') {
+            return Err(Self::invalid(raw, "path has a trailing slash"));
+        }
+        if raw.contains("//") {
+            return Err(Self::invalid(raw, "path contains a doubled separator"));
+        }
+
+        let mut path = Self(String::from("/"));
+        for segment in raw.split('/') {
+            path.push_segment(segment);
+        }
+        Ok(path)
+    }
+
+    fn invalid(path: &str, reason: &str) -> ArtichokeError {
+        ArtichokeError::Vfs(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid VFS path {:?}: {}", path, reason),
+        ))
+    }
+
+    /// The normalized path as a `&str`, e.g. `"/src/lib/foo.rb"`.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The parent directory of this path, if any. The root path has no
+    /// parent.
+    pub fn parent(&self) -> Option<Self> {
+        let idx = self.0.rfind('/')?;
+        if idx == 0 {
+            if self.0.len() == 1 {
+                None
+            } else {
+                Some(Self(String::from("/")))
+            }
+        } else {
+            Some(Self(self.0[..idx].to_string()))
+        }
+    }
+
+    /// Append a single path segment, resolving `.` and `..` in place.
+    ///
+    /// A `..` segment pops the last accumulated segment, if any; it never
+    /// escapes the virtual root.
+    pub fn push_segment(&mut self, segment: &str) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                self.pop();
+            }
+            segment => {
+                if self.0 != "/" {
+                    self.0.push('/');
+                }
+                self.0.push_str(segment);
+            }
+        }
+    }
+
+    /// Remove the last segment, if any. Returns `true` if a segment was
+    /// removed. Popping the root path is a no-op.
+    pub fn pop(&mut self) -> bool {
+        if self.0 == "/" {
+            return false;
+        }
+        match self.0.rfind('/') {
+            Some(0) => {
+                self.0.truncate(1);
+                true
+            }
+            Some(idx) => {
+                self.0.truncate(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Join `path` onto `self`, normalizing the combined path the same way
+    /// as [`VfsPath::new`].
+    pub fn join<T>(&self, path: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        let mut joined = self.clone();
+        for segment in path.as_ref().split('/') {
+            joined.push_segment(segment);
+        }
+        joined
+    }
+
+    /// Join `path` onto `self` like [`VfsPath::join`], but reject a `path`
+    /// whose `..` segments would walk above the virtual root, rather than
+    /// silently clamping at `/`. `..` segments that walk above `self` but
+    /// stay within the root are allowed.
+    ///
+    /// This is used to resolve `require_relative`, where `self` is the
+    /// requiring file's directory: a `require_relative` with more `..`
+    /// segments than that directory is deep fails to resolve rather than
+    /// quietly landing on some unrelated file near the root.
+    pub fn try_join<T>(&self, path: T) -> Result<Self, ArtichokeError>
+    where
+        T: AsRef<str>,
+    {
+        let path = path.as_ref();
+        let mut depth = self.0.split('/').filter(|s| !s.is_empty()).count();
+        let mut joined = self.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    if depth == 0 {
+                        return Err(Self::invalid(
+                            path,
+                            "path escapes the virtual root",
+                        ));
+                    }
+                    depth -= 1;
+                    joined.pop();
+                }
+                segment => {
+                    depth += 1;
+                    joined.push_segment(segment);
+                }
+            }
+        }
+        Ok(joined)
+    }
+}
+
+impl AsRef<str> for VfsPath {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VfsPath;
+
+    #[test]
+    fn collapses_dot_segments() {
+        let path = VfsPath::new("/src/lib/./foo.rb").unwrap();
+        assert_eq!(path.as_str(), "/src/lib/foo.rb");
+    }
+
+    #[test]
+    fn resolves_dotdot_segments() {
+        let path = VfsPath::new("/src/lib/bar/../foo.rb").unwrap();
+        assert_eq!(path.as_str(), "/src/lib/foo.rb");
+    }
+
+    #[test]
+    fn require_and_relative_require_collapse_to_same_key() {
+        let a = VfsPath::new("foo").unwrap();
+        let b = VfsPath::new("./foo").unwrap();
+        let c = VfsPath::new("bar/../foo").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+    }
+
+    #[test]
+    fn dotdot_does_not_escape_root() {
+        let path = VfsPath::new("/../../foo").unwrap();
+        assert_eq!(path.as_str(), "/foo");
+    }
+
+    #[test]
+    fn rejects_empty_path() {
+        assert!(VfsPath::new("").is_err());
+    }
+
+    #[test]
+    fn rejects_trailing_slash() {
+        assert!(VfsPath::new("/src/lib/").is_err());
+    }
+
+    #[test]
+    fn rejects_doubled_separator() {
+        assert!(VfsPath::new("/src//lib").is_err());
+    }
+
+    #[test]
+    fn normalized_path_never_contains_dot_dotdot_or_doubled_separator() {
+        let cases = ["foo", "./foo", "bar/../foo", "a/./b/../c", "/a/b/../../c"];
+        for case in &cases {
+            let path = VfsPath::new(*case).unwrap();
+            for segment in path.as_str().split('/') {
+                assert_ne!(segment, ".");
+                assert_ne!(segment, "..");
+            }
+            assert!(!path.as_str().contains("//"));
+        }
+    }
+
+    #[test]
+    fn try_join_resolves_relative_sibling() {
+        let anchor = VfsPath::new("/src/lib/foo.rb").unwrap().parent().unwrap();
+        let resolved = anchor.try_join("bar.rb").unwrap();
+        assert_eq!(resolved.as_str(), "/src/lib/bar.rb");
+    }
+
+    #[test]
+    fn try_join_resolves_parent_relative_paths() {
+        let anchor = VfsPath::new("/src/lib/nested/foo.rb").unwrap().parent().unwrap();
+        let resolved = anchor.try_join("../bar.rb").unwrap();
+        assert_eq!(resolved.as_str(), "/src/lib/bar.rb");
+    }
+
+    #[test]
+    fn try_join_rejects_escaping_the_virtual_root() {
+        let anchor = VfsPath::new("/foo.rb").unwrap().parent().unwrap();
+        assert!(anchor.try_join("../../etc/passwd").is_err());
+    }
+}
@@ -1,24 +1,34 @@
 use std::path::Path;
 
 use crate::file::File;
-use crate::fs::RUBY_LOAD_PATH;
+use crate::fs::{FileId, LoadRootHandle, ScanMode, VfsPath, RUBY_LOAD_PATH};
 use crate::{Artichoke, ArtichokeError};
 
+/// The Ruby global variable that mirrors [`LoadSources::load_paths`].
+const LOAD_PATH_GLOBAL: &str = "$LOAD_PATH";
+
+/// `$:`, Ruby's short alias for [`LOAD_PATH_GLOBAL`]. Kept pointing at the
+/// same array as `$LOAD_PATH` so either name reflects pushes/prepends made
+/// from Rust or from Ruby.
+const LOAD_PATH_GLOBAL_ALIAS: &str = "$:";
+
 #[allow(clippy::module_name_repetitions)]
 pub trait LoadSources {
     /// Add a Rust-backed Ruby source file to the virtual filesystem. A stub
     /// Ruby file is added to the filesystem and `require` will dynamically
     /// define Ruby items when invoked via `Kernel#require`.
     ///
-    /// If filename is a relative path, the Ruby source is added to the
-    /// filesystem relative to [`RUBY_LOAD_PATH`]. If the path is absolute, the
-    /// file is placed directly on the filesystem. Anscestor directories are
-    /// created automatically.
+    /// If filename is a relative path, the Ruby source is resolved against
+    /// [`Self::load_paths`], in order, just like `Kernel#require`: it is
+    /// added under the first load path entry it is already a file under, or
+    /// under the first load path entry at all if it is new. If the path is
+    /// absolute, the file is placed directly on the filesystem. Anscestor
+    /// directories are created automatically.
     fn def_file<T>(
         &self,
         filename: T,
         require: fn(Self) -> Result<(), ArtichokeError>,
-    ) -> Result<(), ArtichokeError>
+    ) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>;
 
@@ -26,25 +36,219 @@ pub trait LoadSources {
     /// Ruby file is added to the filesystem and [`File::require`] will
     /// dynamically define Ruby items when invoked via `Kernel#require`.
     ///
-    /// If filename is a relative path, the Ruby source is added to the
-    /// filesystem relative to [`RUBY_LOAD_PATH`]. If the path is absolute, the
-    /// file is placed directly on the filesystem. Anscestor directories are
-    /// created automatically.
-    fn def_file_for_type<T, F>(&self, filename: T) -> Result<(), ArtichokeError>
+    /// If filename is a relative path, the Ruby source is resolved against
+    /// [`Self::load_paths`] the same way as [`Self::def_file`]. If the path
+    /// is absolute, the file is placed directly on the filesystem. Anscestor
+    /// directories are created automatically.
+    fn def_file_for_type<T, F>(&self, filename: T) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>,
         F: File;
 
     /// Add a pure Ruby source file to the virtual filesystem.
     ///
-    /// If filename is a relative path, the Ruby source is added to the
-    /// filesystem relative to [`RUBY_LOAD_PATH`]. If the path is absolute, the
-    /// file is placed directly on the filesystem. Anscestor directories are
-    /// created automatically.
-    fn def_rb_source_file<T, F>(&self, filename: T, contents: F) -> Result<(), ArtichokeError>
+    /// If filename is a relative path, the Ruby source is resolved against
+    /// [`Self::load_paths`] the same way as [`Self::def_file`]. If the path
+    /// is absolute, the file is placed directly on the filesystem. Anscestor
+    /// directories are created automatically.
+    fn def_rb_source_file<T, F>(&self, filename: T, contents: F) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>,
         F: AsRef<[u8]>;
+
+    /// Add a Rust-backed Ruby source file that is reachable via
+    /// `require_relative` from `anchor`, rather than through `$LOAD_PATH`.
+    ///
+    /// This is [`LoadSources::def_file`] for the case where the Rust-backed
+    /// file is meant to live alongside a specific Ruby source file -- for
+    /// example a pure-Ruby file that does `require_relative "foo_ext"` to
+    /// pull in a Rust-backed extension next to it. `anchor` is the VFS path
+    /// of the requiring file, not its directory.
+    fn def_file_relative_to<T, U>(
+        &self,
+        anchor: T,
+        filename: U,
+        require: fn(Self) -> Result<(), ArtichokeError>,
+    ) -> Result<FileId, ArtichokeError>
+    where
+        T: AsRef<str>,
+        U: AsRef<str>;
+
+    /// Mirror the real directory `real_dir` into the virtual filesystem
+    /// under `vfs_prefix`, so Ruby code can `require` files that live on
+    /// disk without having registered each one through [`Self::def_rb_source_file`].
+    ///
+    /// `mode` chooses whether `real_dir` is scanned once, or re-scanned by
+    /// later calls to [`Self::poll_load_root`] to pick up edits made after
+    /// the interpreter started.
+    fn add_load_root<T, U>(
+        &self,
+        real_dir: T,
+        vfs_prefix: U,
+        mode: ScanMode,
+    ) -> Result<LoadRootHandle, ArtichokeError>
+    where
+        T: AsRef<Path>,
+        U: AsRef<str>;
+
+    /// Re-scan the load root registered under `handle`, applying any
+    /// create/modify/delete changes observed on disk back into the virtual
+    /// filesystem and invalidating the `require` state of any file whose
+    /// contents changed.
+    fn poll_load_root(&self, handle: LoadRootHandle) -> Result<(), ArtichokeError>;
+
+    /// Append `path` to the end of `$LOAD_PATH`, searched last when
+    /// resolving a relative `require`. Also pushes `path` onto the Ruby
+    /// `$LOAD_PATH`/`$:` globals, so `require` calls issued from Ruby see it
+    /// too.
+    fn push_load_path<T>(&self, path: T) -> Result<(), ArtichokeError>
+    where
+        T: AsRef<str>;
+
+    /// Insert `path` at the front of `$LOAD_PATH`, searched first when
+    /// resolving a relative `require`. Also prepends `path` onto the Ruby
+    /// `$LOAD_PATH`/`$:` globals, so `require` calls issued from Ruby see it
+    /// too.
+    fn prepend_load_path<T>(&self, path: T) -> Result<(), ArtichokeError>
+    where
+        T: AsRef<str>;
+
+    /// A snapshot of the internal load path registry, in search order.
+    /// [`RUBY_LOAD_PATH`] is the first entry unless callers have reordered or
+    /// displaced it with [`Self::prepend_load_path`] or by mutating
+    /// `$LOAD_PATH` from Ruby. This does not re-read the `$LOAD_PATH` global:
+    /// Ruby-side changes appear here only once a later relative
+    /// [`Self::def_file`]/[`Self::def_rb_source_file`],
+    /// [`Self::push_load_path`], or [`Self::prepend_load_path`] call has
+    /// pulled them in.
+    fn load_paths(&self) -> Vec<VfsPath>;
+}
+
+/// Resolve `filename` to its canonical [`VfsPath`].
+///
+/// An absolute `filename` is normalized as-is. A relative `filename` is
+/// resolved against `interp`'s load path (`$LOAD_PATH`), in order: it keys to
+/// the first entry under which it is already a file, or the first entry at
+/// all if it is new. Either way the result is the single normalized key that
+/// `require "foo"`, `require "./foo"`, and `require "bar/../foo"` all
+/// resolve to.
+///
+/// Re-reads the `$LOAD_PATH` global first, so a relative filename resolves
+/// against whatever order Ruby code has last left it in, not just the order
+/// established by [`LoadSources::push_load_path`]/
+/// [`LoadSources::prepend_load_path`].
+fn resolve_def_path(interp: &Artichoke, filename: &str) -> Result<VfsPath, ArtichokeError> {
+    if filename.starts_with('/') {
+        VfsPath::new(filename)
+    } else {
+        sync_load_path_from_global(interp)?;
+        let api = interp.0.borrow();
+        api.vfs.resolve_in_load_path(filename)
+    }
+}
+
+/// Resolve `relative` against the directory of `anchor`, the VFS path of the
+/// file currently calling `require_relative`.
+///
+/// Returns an error if `relative`'s `..` segments would walk past the
+/// virtual root.
+fn resolve_relative_path(anchor: &VfsPath, relative: &str) -> Result<VfsPath, ArtichokeError> {
+    let dir = anchor.parent().unwrap_or_else(|| anchor.clone());
+    dir.try_join(relative)
+}
+
+/// Rebuild the `$LOAD_PATH`/`$:` globals from `interp`'s internal load path
+/// registry, so that Ruby code reading or iterating `$LOAD_PATH` sees the
+/// effect of [`LoadSources::push_load_path`] and
+/// [`LoadSources::prepend_load_path`].
+fn sync_load_path_globals(interp: &Artichoke) -> Result<(), ArtichokeError> {
+    let paths = interp.load_paths();
+    let paths: Vec<&str> = paths.iter().map(VfsPath::as_str).collect();
+    let value = interp.convert(paths);
+    interp.set_global_variable(LOAD_PATH_GLOBAL, &value)?;
+    interp.set_global_variable(LOAD_PATH_GLOBAL_ALIAS, &value)?;
+    Ok(())
+}
+
+/// Pull `$LOAD_PATH` back from its Ruby global into the internal load-path
+/// registry, so that Ruby code that mutates it directly -- `$LOAD_PATH.push
+/// "..."`, `$LOAD_PATH.unshift "..."` -- changes where a later relative
+/// [`LoadSources::def_file`]/[`LoadSources::def_rb_source_file`] call (and
+/// so, transitively, `require`) looks, not just calls to
+/// [`LoadSources::push_load_path`]/[`LoadSources::prepend_load_path`] made
+/// from Rust.
+///
+/// No-op if the global hasn't been set yet, e.g. before the first
+/// [`LoadSources::push_load_path`]/[`LoadSources::prepend_load_path`] call
+/// seeds it.
+fn sync_load_path_from_global(interp: &Artichoke) -> Result<(), ArtichokeError> {
+    let value = match interp.get_global_variable(LOAD_PATH_GLOBAL)? {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    let paths: Vec<String> = interp.try_convert(value)?;
+    let paths = paths
+        .into_iter()
+        .map(VfsPath::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let api = interp.0.borrow();
+    api.vfs.set_load_path(paths);
+    Ok(())
+}
+
+/// Resolve a `require_relative "path"` call issued while `interp` is
+/// executing the file at the top of its active-file stack.
+///
+/// Returns an error if there is no active file, e.g. `require_relative` was
+/// called from top-level `eval` rather than from a `require`d file. Callers
+/// in `Kernel#require_relative` surface that as a Ruby `LoadError`.
+pub fn require_relative(interp: &Artichoke, relative: &str) -> Result<VfsPath, ArtichokeError> {
+    let api = interp.0.borrow();
+    let anchor = api.vfs.active_file().ok_or_else(|| {
+        ArtichokeError::Vfs(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "require_relative called with no active file to anchor to",
+        ))
+    })?;
+    let anchor = api.vfs.path(anchor);
+    resolve_relative_path(&anchor, relative)
+}
+
+/// Run the `require` callback registered for `path`, unless `path` has
+/// already been required.
+///
+/// This is what `Kernel#require`/`Kernel#require_relative` call once they
+/// have resolved a filename to a [`VfsPath`]. While the callback runs,
+/// `path`'s `FileId` is pushed onto the VFS's active-file stack, so that a
+/// `require_relative` nested inside it -- directly, or transitively through
+/// another file it requires -- anchors to `path`, not to whichever file
+/// required `path` in the first place. The push/pop is paired even if the
+/// callback errors, so a failed require never leaves a stale anchor behind.
+///
+/// Returns `true` if `path` had not been required yet (its callback, if it
+/// has one, has now run), `false` if `path` was already required, mirroring
+/// `Kernel#require`'s return value.
+pub fn require(interp: &Artichoke, path: &VfsPath) -> Result<bool, ArtichokeError> {
+    let (id, require_fn, already_required) = {
+        let api = interp.0.borrow();
+        let id = api.vfs.file_id(path);
+        let metadata = api.vfs.metadata(path).unwrap_or_default();
+        (id, metadata.require, metadata.already_required)
+    };
+    if already_required {
+        return Ok(false);
+    }
+    if let Some(require_fn) = require_fn {
+        interp.0.borrow().vfs.push_active_file(id);
+        let result = require_fn(interp.clone());
+        interp.0.borrow().vfs.pop_active_file();
+        result?;
+    }
+    let api = interp.0.borrow();
+    let mut metadata = api.vfs.metadata(path).unwrap_or_default();
+    metadata.already_required = true;
+    api.vfs.set_metadata(path, metadata)?;
+    Ok(true)
 }
 
 impl LoadSources for Artichoke {
@@ -52,35 +256,32 @@ impl LoadSources for Artichoke {
         &self,
         filename: T,
         require: fn(Self) -> Result<(), ArtichokeError>,
-    ) -> Result<(), ArtichokeError>
+    ) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>,
     {
+        let path = resolve_def_path(self, filename.as_ref())?;
         let api = self.0.borrow();
-        let path = Path::new(filename.as_ref());
-        let path = if path.is_relative() {
-            Path::new(RUBY_LOAD_PATH).join(path)
-        } else {
-            path.to_path_buf()
-        };
         if let Some(parent) = path.parent() {
-            api.vfs.create_dir_all(parent)?;
-        }
-        if !api.vfs.is_file(&path) {
-            let contents = format!("# virtual source file -- {:?}", &path);
-            api.vfs.write_file(&path, contents)?;
+            api.vfs.create_dir_all(&parent)?;
         }
+        let id = if !api.vfs.is_file(&path) {
+            let contents = format!("# virtual source file -- {}", &path);
+            api.vfs.write_file(&path, contents)?
+        } else {
+            api.vfs.file_id(&path)
+        };
         let mut metadata = api.vfs.metadata(&path).unwrap_or_default();
         metadata.require = Some(require);
         api.vfs.set_metadata(&path, metadata)?;
         trace!(
-            "Added rust-backed ruby source file with require func -- {:?}",
+            "Added rust-backed ruby source file with require func -- {}",
             &path
         );
-        Ok(())
+        Ok(id)
     }
 
-    fn def_file_for_type<T, F>(&self, filename: T) -> Result<(), ArtichokeError>
+    fn def_file_for_type<T, F>(&self, filename: T) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>,
         F: File,
@@ -88,25 +289,223 @@ impl LoadSources for Artichoke {
         self.def_file(filename.as_ref(), F::require)
     }
 
-    fn def_rb_source_file<T, F>(&self, filename: T, contents: F) -> Result<(), ArtichokeError>
+    fn def_rb_source_file<T, F>(&self, filename: T, contents: F) -> Result<FileId, ArtichokeError>
     where
         T: AsRef<str>,
         F: AsRef<[u8]>,
     {
+        let path = resolve_def_path(self, filename.as_ref())?;
         let api = self.0.borrow();
-        let path = Path::new(filename.as_ref());
-        let path = if path.is_relative() {
-            Path::new(RUBY_LOAD_PATH).join(path)
-        } else {
-            path.to_path_buf()
-        };
         if let Some(parent) = path.parent() {
-            api.vfs.create_dir_all(parent)?;
+            api.vfs.create_dir_all(&parent)?;
         }
-        api.vfs.write_file(&path, contents.as_ref())?;
+        let id = api.vfs.write_file(&path, contents.as_ref())?;
         let metadata = api.vfs.metadata(&path).unwrap_or_default();
         api.vfs.set_metadata(&path, metadata)?;
-        trace!("Added pure ruby source file -- {:?}", &path);
+        trace!("Added pure ruby source file -- {}", &path);
+        Ok(id)
+    }
+
+    fn def_file_relative_to<T, U>(
+        &self,
+        anchor: T,
+        filename: U,
+        require: fn(Self) -> Result<(), ArtichokeError>,
+    ) -> Result<FileId, ArtichokeError>
+    where
+        T: AsRef<str>,
+        U: AsRef<str>,
+    {
+        let anchor = VfsPath::new(anchor.as_ref())?;
+        let path = resolve_relative_path(&anchor, filename.as_ref())?;
+        let api = self.0.borrow();
+        if let Some(parent) = path.parent() {
+            api.vfs.create_dir_all(&parent)?;
+        }
+        let id = if !api.vfs.is_file(&path) {
+            let contents = format!("# virtual source file -- {}", &path);
+            api.vfs.write_file(&path, contents)?
+        } else {
+            api.vfs.file_id(&path)
+        };
+        let mut metadata = api.vfs.metadata(&path).unwrap_or_default();
+        metadata.require = Some(require);
+        api.vfs.set_metadata(&path, metadata)?;
+        trace!(
+            "Added rust-backed ruby source file reachable via require_relative from {} -- {}",
+            &anchor,
+            &path
+        );
+        Ok(id)
+    }
+
+    fn add_load_root<T, U>(
+        &self,
+        real_dir: T,
+        vfs_prefix: U,
+        mode: ScanMode,
+    ) -> Result<LoadRootHandle, ArtichokeError>
+    where
+        T: AsRef<Path>,
+        U: AsRef<str>,
+    {
+        let api = self.0.borrow();
+        let vfs_prefix = VfsPath::new(vfs_prefix.as_ref())?;
+        let handle = api
+            .vfs
+            .add_load_root(real_dir.as_ref().to_path_buf(), vfs_prefix, mode)?;
+        trace!(
+            "Mirrored load root {:?} into the VFS -- {:?}",
+            real_dir.as_ref(),
+            mode
+        );
+        Ok(handle)
+    }
+
+    fn poll_load_root(&self, handle: LoadRootHandle) -> Result<(), ArtichokeError> {
+        let api = self.0.borrow();
+        let events = api.vfs.poll_load_root(handle)?;
+        if !events.is_empty() {
+            trace!("Applied {} VFS change(s) from load root poll", events.len());
+        }
+        Ok(())
+    }
+
+    fn push_load_path<T>(&self, path: T) -> Result<(), ArtichokeError>
+    where
+        T: AsRef<str>,
+    {
+        sync_load_path_from_global(self)?;
+        let api = self.0.borrow();
+        let path = VfsPath::new(path.as_ref())?;
+        trace!("Appended {} to $LOAD_PATH", &path);
+        api.vfs.push_load_path(path);
+        drop(api);
+        sync_load_path_globals(self)
+    }
+
+    fn prepend_load_path<T>(&self, path: T) -> Result<(), ArtichokeError>
+    where
+        T: AsRef<str>,
+    {
+        sync_load_path_from_global(self)?;
+        let api = self.0.borrow();
+        let path = VfsPath::new(path.as_ref())?;
+        trace!("Prepended {} to $LOAD_PATH", &path);
+        api.vfs.prepend_load_path(path);
+        drop(api);
+        sync_load_path_globals(self)
+    }
+
+    fn load_paths(&self) -> Vec<VfsPath> {
+        let api = self.0.borrow();
+        api.vfs.load_paths()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::{require, LoadSources};
+    use crate::fs::VfsPath;
+    use crate::Artichoke;
+
+    thread_local! {
+        static OBSERVED_ANCHOR: RefCell<Option<VfsPath>> = RefCell::new(None);
+    }
+
+    fn inner_require(interp: Artichoke) -> Result<(), crate::ArtichokeError> {
+        let resolved = super::require_relative(&interp, "sibling")?;
+        OBSERVED_ANCHOR.with(|anchor| *anchor.borrow_mut() = Some(resolved));
         Ok(())
     }
+
+    fn outer_require(interp: Artichoke) -> Result<(), crate::ArtichokeError> {
+        let inner = VfsPath::new("/src/lib/nested/inner.rb").expect("valid path");
+        require(&interp, &inner)?;
+        Ok(())
+    }
+
+    #[test]
+    fn nested_require_relative_anchors_to_the_file_currently_executing() {
+        let interp = crate::interpreter().expect("init");
+        interp
+            .def_file("outer.rb", outer_require)
+            .expect("define outer.rb");
+        interp
+            .def_file("nested/inner.rb", inner_require)
+            .expect("define nested/inner.rb");
+
+        let outer = VfsPath::new("/src/lib/outer.rb").expect("valid path");
+        let required = require(&interp, &outer).expect("require outer.rb");
+        assert!(required);
+
+        // `inner.rb`'s `require_relative "sibling"` must resolve against
+        // `inner.rb`'s directory, not `outer.rb`'s -- even though `outer.rb`
+        // is the one that was originally `require`d. Resolution appends no
+        // extension.
+        OBSERVED_ANCHOR.with(|anchor| {
+            assert_eq!(
+                anchor.borrow().as_ref().map(VfsPath::as_str),
+                Some("/src/lib/nested/sibling")
+            );
+        });
+
+        // Both `push_active_file` calls must be paired with a pop: once
+        // `outer.rb` is done requiring, nothing is left on the stack.
+        assert!(interp.0.borrow().vfs.active_file().is_none());
+    }
+
+    #[test]
+    fn require_relative_with_no_active_file_is_an_error() {
+        let interp = crate::interpreter().expect("init");
+        assert!(super::require_relative(&interp, "foo").is_err());
+    }
+
+    #[test]
+    fn require_does_not_rerun_an_already_required_file() {
+        thread_local! {
+            static CALLS: RefCell<u32> = RefCell::new(0);
+        }
+        fn counting_require(_interp: Artichoke) -> Result<(), crate::ArtichokeError> {
+            CALLS.with(|calls| *calls.borrow_mut() += 1);
+            Ok(())
+        }
+
+        let interp = crate::interpreter().expect("init");
+        interp
+            .def_file("counted.rb", counting_require)
+            .expect("define counted.rb");
+        let path = VfsPath::new("/src/lib/counted.rb").expect("valid path");
+
+        assert!(require(&interp, &path).expect("first require"));
+        assert!(!require(&interp, &path).expect("second require"));
+        CALLS.with(|calls| assert_eq!(*calls.borrow(), 1));
+    }
+
+    #[test]
+    fn ruby_side_load_path_mutation_is_seen_by_a_later_relative_def() {
+        let interp = crate::interpreter().expect("init");
+
+        // Seed the `$LOAD_PATH` global the way `push_load_path` would, then
+        // simulate Ruby code running `$LOAD_PATH = ["/gems/b", "/gems/a"]` by
+        // setting the global directly, without going through
+        // `LoadSources::prepend_load_path`.
+        interp.push_load_path("/gems/a").expect("push /gems/a");
+        let mutated = interp.convert(vec!["/gems/b", "/gems/a"]);
+        interp
+            .set_global_variable(super::LOAD_PATH_GLOBAL, &mutated)
+            .expect("simulate Ruby mutating $LOAD_PATH");
+
+        interp
+            .def_rb_source_file("foo.rb", "# foo")
+            .expect("define foo.rb");
+
+        // `foo.rb` must land under `/gems/b`, the first entry Ruby code left
+        // in `$LOAD_PATH`, not `/src/lib`, the first entry of the registry
+        // before the global was re-read.
+        let path = VfsPath::new("/gems/b/foo.rb").expect("valid path");
+        assert!(interp.0.borrow().vfs.is_file(&path));
+    }
 }